@@ -0,0 +1,247 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchField {
+    Class,
+    Instance,
+    Title,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchKind {
+    Literal,
+    Regex,
+}
+
+fn default_field() -> MatchField {
+    MatchField::Class
+}
+
+fn default_kind() -> MatchKind {
+    MatchKind::Literal
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Rule {
+    #[serde(default = "default_field")]
+    pub field: MatchField,
+    #[serde(default = "default_kind")]
+    pub kind: MatchKind,
+    pub pattern: String,
+    pub label: String,
+}
+
+fn default_separator() -> String {
+    " ".to_string()
+}
+
+fn default_format() -> String {
+    "{num}: {name}".to_string()
+}
+
+fn default_fallback_to_class_initial() -> bool {
+    false
+}
+
+fn default_debounce_millis() -> u64 {
+    75
+}
+
+fn default_resync_interval_secs() -> u64 {
+    30
+}
+
+// Deserialized from `~/.config/i3-autonamer/config.toml`.
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    #[serde(default = "default_rules")]
+    pub rules: Vec<Rule>,
+    #[serde(default = "default_separator")]
+    pub separator: String,
+    // `{num}` and `{name}` are substituted with the workspace number and
+    // the joined labels.
+    #[serde(default = "default_format")]
+    pub format: String,
+    // Off by default so a zero-config setup matches the old hardcoded
+    // behavior: an unmapped window contributes nothing to the name.
+    #[serde(default = "default_fallback_to_class_initial")]
+    pub fallback_to_class_initial: bool,
+    // How long to wait for a quiet period after a window/workspace event
+    // before recomputing workspace names, so a burst of events collapses
+    // into a single rename pass.
+    #[serde(default = "default_debounce_millis")]
+    pub debounce_millis: u64,
+    // How often to force a full rename pass even without a window/workspace
+    // event, as a safety net against missed i3 events.
+    #[serde(default = "default_resync_interval_secs")]
+    pub resync_interval_secs: u64,
+}
+
+fn default_rules() -> Vec<Rule> {
+    [
+        ("Alacritty", "A"),
+        ("Joplin", "J"),
+        ("Firefox", "F"),
+        ("Code", "C"),
+    ]
+    .iter()
+    .map(|(pattern, label)| Rule {
+        field: MatchField::Class,
+        kind: MatchKind::Literal,
+        pattern: pattern.to_string(),
+        label: label.to_string(),
+    })
+    .collect()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            rules: default_rules(),
+            separator: default_separator(),
+            format: default_format(),
+            fallback_to_class_initial: default_fallback_to_class_initial(),
+            debounce_millis: default_debounce_millis(),
+            resync_interval_secs: default_resync_interval_secs(),
+        }
+    }
+}
+
+impl Config {
+    // Falls back to the built-in defaults if no file is present.
+    pub fn load() -> Result<Config> {
+        Self::load_from(&default_config_path()?)
+    }
+
+    fn load_from(path: &Path) -> Result<Config> {
+        if !path.exists() {
+            log::debug!("No config file at {:?}, using defaults", path);
+            return Ok(Config::default());
+        }
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {:?}", path))?;
+        let config: Config = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file {:?}", path))?;
+        Ok(config)
+    }
+
+    // Precompiles regexes once instead of on every window lookup.
+    pub fn compile(&self) -> Result<CompiledConfig> {
+        // `tokio::time::interval` panics if given a zero period, and a zero
+        // resync interval would busy-loop the sender task anyway.
+        if self.resync_interval_secs == 0 {
+            bail!("resync_interval_secs must be greater than 0");
+        }
+        if self.debounce_millis == 0 {
+            bail!("debounce_millis must be greater than 0");
+        }
+        let rules = self
+            .rules
+            .iter()
+            .map(CompiledRule::compile)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(CompiledConfig {
+            rules,
+            separator: self.separator.clone(),
+            format: self.format.clone(),
+            fallback_to_class_initial: self.fallback_to_class_initial,
+            debounce: std::time::Duration::from_millis(self.debounce_millis),
+            resync_interval: std::time::Duration::from_secs(self.resync_interval_secs),
+        })
+    }
+}
+
+fn default_config_path() -> Result<PathBuf> {
+    let mut path = dirs::config_dir().context("Could not determine config directory")?;
+    path.push("i3-autonamer");
+    path.push("config.toml");
+    Ok(path)
+}
+
+enum Pattern {
+    Literal(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn is_match(&self, value: &str) -> bool {
+        match self {
+            Pattern::Literal(literal) => literal == value,
+            Pattern::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+pub struct CompiledRule {
+    field: MatchField,
+    pattern: Pattern,
+    label: String,
+}
+
+impl CompiledRule {
+    fn compile(rule: &Rule) -> Result<CompiledRule> {
+        let pattern = match rule.kind {
+            MatchKind::Literal => Pattern::Literal(rule.pattern.clone()),
+            MatchKind::Regex => Pattern::Regex(
+                Regex::new(&rule.pattern)
+                    .with_context(|| format!("Invalid regex pattern {:?}", rule.pattern))?,
+            ),
+        };
+        Ok(CompiledRule {
+            field: rule.field,
+            pattern,
+            label: rule.label.clone(),
+        })
+    }
+}
+
+pub struct WindowProperties<'a> {
+    pub class: Option<&'a str>,
+    pub instance: Option<&'a str>,
+    pub title: Option<&'a str>,
+}
+
+pub struct CompiledConfig {
+    rules: Vec<CompiledRule>,
+    pub separator: String,
+    pub format: String,
+    pub fallback_to_class_initial: bool,
+    pub debounce: std::time::Duration,
+    pub resync_interval: std::time::Duration,
+}
+
+impl CompiledConfig {
+    pub fn label_for(&self, window: &WindowProperties) -> Option<String> {
+        for rule in &self.rules {
+            let value = match rule.field {
+                MatchField::Class => window.class,
+                MatchField::Instance => window.instance,
+                MatchField::Title => window.title,
+            };
+            if let Some(value) = value {
+                if rule.pattern.is_match(value) {
+                    return Some(rule.label.clone());
+                }
+            }
+        }
+        if self.fallback_to_class_initial {
+            return window
+                .class
+                .and_then(|class| class.chars().next())
+                .map(|c| c.to_string());
+        }
+        None
+    }
+
+    pub fn format_workspace_name(&self, num: i32, name: &str) -> String {
+        self.format
+            .replace("{num}", &num.to_string())
+            .replace("{name}", name)
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_i3ipc::{reply, reply::Node, I3};
+
+type Responder<T> = oneshot::Sender<anyhow::Result<T, std::io::Error>>;
+
+enum Command {
+    RunCommand {
+        payload: String,
+        resp: Responder<Vec<reply::Success>>,
+    },
+    GetTree {
+        resp: Responder<Node>,
+    },
+}
+
+struct I3HandleInner {
+    tx: mpsc::Sender<Command>,
+}
+
+/// A cheaply cloneable handle to the task that owns the i3 IPC connection
+/// used for running commands and fetching the tree, replacing the ad-hoc
+/// `Command` channel callers used to build by hand.
+#[derive(Clone)]
+pub struct I3Handle(Arc<I3HandleInner>);
+
+impl I3Handle {
+    /// Connect to i3 and spawn the task that serializes commands against
+    /// that connection, returning a handle to it and the task's `JoinHandle`.
+    pub fn spawn() -> (I3Handle, JoinHandle<Result<()>>) {
+        let (tx, mut rx) = mpsc::channel::<Command>(10);
+        let join_handle = tokio::spawn(async move {
+            let mut i3 = I3::connect().await?;
+            while let Some(cmd) = rx.recv().await {
+                match cmd {
+                    Command::RunCommand { payload, resp } => {
+                        log::debug!("RunCommand {}", payload);
+                        let res = i3.run_command(payload).await;
+                        let _ = resp.send(res);
+                    }
+                    Command::GetTree { resp } => {
+                        let res = i3.get_tree().await;
+                        let _ = resp.send(res);
+                    }
+                }
+            }
+            log::debug!("Receiver loop ended");
+            Ok(())
+        });
+        (I3Handle(Arc::new(I3HandleInner { tx })), join_handle)
+    }
+
+    /// Fetch the current window tree from i3.
+    pub async fn get_tree(&self) -> Result<Node> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.0.tx.send(Command::GetTree { resp: resp_tx }).await?;
+        log::debug!("Waiting for tree");
+        let tree = resp_rx.await??;
+        log::debug!("We got tree!");
+        log::debug!("{:?}", tree);
+        Ok(tree)
+    }
+
+    /// Run an i3 command, e.g. `rename workspace "1" to "1: F"`.
+    pub async fn run_command(&self, payload: String) -> Result<Vec<reply::Success>> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.0
+            .tx
+            .send(Command::RunCommand {
+                payload,
+                resp: resp_tx,
+            })
+            .await?;
+        Ok(resp_rx.await??)
+    }
+}
@@ -1,22 +1,34 @@
+mod config;
+mod i3handle;
+
 use anyhow::{Error, Result};
-use std;
 use std::collections::{HashMap, HashSet};
-use tokio::sync::{mpsc, oneshot};
+use std::sync::Arc;
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::task::JoinHandle;
 use tokio_i3ipc::{
     event::{Event, Subscribe, WindowChange, WorkspaceChange},
-    reply,
     reply::{Node, NodeType},
     I3,
 };
+use tokio_stream::wrappers::{IntervalStream, SignalStream};
 use tokio_stream::StreamExt;
 
-type Lookup = HashMap<String, String>;
+use config::{CompiledConfig, WindowProperties};
+use i3handle::I3Handle;
+
+// A single dispatch point for everything the sender loop reacts to: an i3
+// event, a resync tick, or a shutdown signal.
+enum Input {
+    I3(Event),
+    Tick,
+    Signal(SignalKind),
+}
 
-fn get_leaf_content_nodes<'a>(node: &'a Node) -> Vec<&Node> {
+fn get_leaf_content_nodes(node: &Node) -> Vec<&Node> {
     get_nodes_of_type(node, NodeType::Con)
         .flat_map(|n| {
-            if n.nodes.len() == 0 {
+            if n.nodes.is_empty() {
                 vec![n]
             } else {
                 get_leaf_content_nodes(n)
@@ -25,39 +37,43 @@ fn get_leaf_content_nodes<'a>(node: &'a Node) -> Vec<&Node> {
         .collect()
 }
 
-fn get_workspace_name(workspace_node: &Node, lookup: &Lookup) -> String {
+// Joined, deduped labels for every window on a workspace.
+fn get_workspace_name(workspace_node: &Node, config: &CompiledConfig) -> String {
     let names = get_leaf_content_nodes(workspace_node)
         .iter()
         .filter_map(|n| {
-            let class_name = (n.window_properties).as_ref()?.class.as_ref()?;
-            log::debug!("class__name: {}", class_name);
-            lookup.get(class_name)
+            let window_properties = (n.window_properties).as_ref()?;
+            log::debug!("window_properties: {:?}", window_properties);
+            config.label_for(&WindowProperties {
+                class: window_properties.class.as_deref(),
+                instance: window_properties.instance.as_deref(),
+                title: window_properties.title.as_deref(),
+            })
         })
-        .cloned()
         .collect::<HashSet<_>>();
-    names.into_iter().collect::<Vec<_>>().join(" ")
+    names
+        .into_iter()
+        .collect::<Vec<_>>()
+        .join(&config.separator)
 }
 
-fn get_nodes_of_type<'a>(node: &'a Node, node_type: NodeType) -> impl Iterator<Item = &'a Node> {
+fn get_nodes_of_type(node: &Node, node_type: NodeType) -> impl Iterator<Item = &Node> {
     node.nodes.iter().filter(move |n| n.node_type == node_type)
 }
 
 fn get_workspace_nodes(root: &Node) -> impl Iterator<Item = &Node> {
     assert!(root.node_type == NodeType::Root);
-    get_nodes_of_type(&root, NodeType::Output)
-        .map(|n| get_nodes_of_type(n, NodeType::Con))
-        .flatten()
-        .map(|n| get_nodes_of_type(n, NodeType::Workspace))
-        .flatten()
+    get_nodes_of_type(root, NodeType::Output)
+        .flat_map(|n| get_nodes_of_type(n, NodeType::Con))
+        .flat_map(|n| get_nodes_of_type(n, NodeType::Workspace))
 }
 
-async fn get_workspace_rename_commands(tree: &Node) -> Result<Vec<String>> {
-    let mut lookup: Lookup = HashMap::new();
-    lookup.insert("Alacritty".to_string(), 'A'.to_string());
-    lookup.insert("Joplin".to_string(), 'J'.to_string());
-    lookup.insert("Firefox".to_string(), 'F'.to_string());
-    lookup.insert("Code".to_string(), 'C'.to_string());
-    let workspace_nodes = get_workspace_nodes(&tree);
+// `rename workspace` commands for every numbered workspace in the tree.
+async fn get_workspace_rename_commands(
+    tree: &Node,
+    config: &CompiledConfig,
+) -> Result<Vec<String>> {
+    let workspace_nodes = get_workspace_nodes(tree);
     // log::debug!("root: {:#?}", root);
     Ok(workspace_nodes
         .filter_map(|workspace_node| {
@@ -65,9 +81,9 @@ async fn get_workspace_rename_commands(tree: &Node) -> Result<Vec<String>> {
             if num < 1 {
                 return None;
             }
-            let name = get_workspace_name(workspace_node, &lookup);
-            let full_name = if name.len() > 0 {
-                format!("{}: {}", num, name)
+            let name = get_workspace_name(workspace_node, config);
+            let full_name = if !name.is_empty() {
+                config.format_workspace_name(num, &name)
             } else {
                 format!("{}", num)
             };
@@ -81,67 +97,122 @@ async fn get_workspace_rename_commands(tree: &Node) -> Result<Vec<String>> {
         .collect())
 }
 
-type Responder<T> = oneshot::Sender<anyhow::Result<T, std::io::Error>>;
-
-#[derive(Debug)]
-enum Command {
-    RunCommand {
-        payload: String,
-        resp: Responder<Vec<reply::Success>>,
-    },
-    GetTree {
-        resp: Responder<Node>,
-    },
+// Keyed by workspace number, so it can be restored on shutdown.
+fn snapshot_original_names(tree: &Node) -> HashMap<i32, String> {
+    get_workspace_nodes(tree)
+        .filter_map(|workspace_node| {
+            let num = workspace_node.num?;
+            if num < 1 {
+                return None;
+            }
+            Some((num, workspace_node.name.clone()?))
+        })
+        .collect()
+}
+
+// Rename every workspace back to the name it had before we started mangling it.
+async fn restore_workspace_names(
+    i3: &I3Handle,
+    original_names: &HashMap<i32, String>,
+) -> Result<()> {
+    let tree = i3.get_tree().await?;
+    for workspace_node in get_workspace_nodes(&tree) {
+        let num = match workspace_node.num {
+            Some(num) if num >= 1 => num,
+            _ => continue,
+        };
+        // Workspaces created after startup have no snapshot entry; fall
+        // back to the bare numbered name rather than leaving them mangled.
+        let fallback_name = num.to_string();
+        let original_name = original_names.get(&num).unwrap_or(&fallback_name);
+        let current_name = workspace_node.name.clone().unwrap();
+        if &current_name == original_name {
+            continue;
+        }
+        log::debug!("Restoring workspace {} to {}", num, original_name);
+        i3.run_command(format!(
+            "rename workspace \"{}\" to \"{}\"",
+            current_name, original_name
+        ))
+        .await?;
+    }
+    Ok(())
 }
 
-fn update_workspace_names(tx: &mpsc::Sender<Command>) -> JoinHandle<Result<()>> {
-    let tx2 = tx.clone();
-    return tokio::spawn(async move {
-        let (resp_tx, resp_rx) = oneshot::channel();
-        let cmd = Command::GetTree { resp: resp_tx };
-        tx2.send(cmd).await?;
-        log::debug!("Waiting for tree");
-        let tree = resp_rx.await?;
-        log::debug!("We got tree!");
-        log::debug!("{:?}", tree);
-        let commands = get_workspace_rename_commands(&tree?).await?;
+// Fetch the tree and run a full rename pass for every workspace.
+fn update_workspace_names(i3: &I3Handle, config: &Arc<CompiledConfig>) -> JoinHandle<Result<()>> {
+    let i3 = i3.clone();
+    let config = config.clone();
+    tokio::spawn(async move {
+        let tree = i3.get_tree().await?;
+        let commands = get_workspace_rename_commands(&tree, &config).await?;
         for command in commands {
             // log::debug!("Command: {}", command);
-            let tx3 = tx2.clone();
-            tokio::spawn(async move {
-                let (resp_tx, resp_rx) = oneshot::channel();
-                let cmd = Command::RunCommand {
-                    payload: command,
-                    resp: resp_tx,
-                };
-                if tx3.send(cmd).await.is_err() {
-                    log::debug!("Error when moving");
-                    return;
-                };
-                let _ = resp_rx.await;
-            })
-            .await?;
+            i3.run_command(command).await?;
         }
-        return Ok(());
-    });
+        Ok(())
+    })
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
     flexi_logger::Logger::try_with_env()?.start()?;
-    let (tx, mut rx) = mpsc::channel::<Command>(10);
+    let config = Arc::new(config::Config::load()?.compile()?);
+    let (i3, r_handle) = I3Handle::spawn();
+
+    let original_names = snapshot_original_names(&i3.get_tree().await?);
 
     let s_handle = tokio::spawn(async move {
+        let config = config.clone();
         let mut event_listener = {
             let mut i3 = I3::connect().await?;
             i3.subscribe([Subscribe::Window, Subscribe::Workspace])
                 .await?;
             i3.listen()
         };
+        let sigint = signal(SignalKind::interrupt())?;
+        let sigterm = signal(SignalKind::terminate())?;
+
+        let ticks =
+            IntervalStream::new(tokio::time::interval(config.resync_interval)).map(|_| Input::Tick);
+        let sigint_events =
+            SignalStream::new(sigint).map(|_| Input::Signal(SignalKind::interrupt()));
+        let sigterm_events =
+            SignalStream::new(sigterm).map(|_| Input::Signal(SignalKind::terminate()));
+        let mut signals_and_ticks = ticks.merge(sigint_events).merge(sigterm_events);
+
+        let mut dirty = false;
+        let debounce = tokio::time::sleep(config.debounce);
+        tokio::pin!(debounce);
 
-        while let Some(event) = event_listener.next().await {
-            match event? {
-                Event::Window(window_data) => {
+        loop {
+            let input = tokio::select! {
+                // Polled on its own arm, rather than merged with the
+                // never-ending tick/signal streams, so a closed i3 IPC
+                // connection ends this loop promptly instead of being
+                // masked by the other streams still producing input.
+                event = event_listener.next() => {
+                    match event {
+                        Some(event) => Some(Input::I3(event?)),
+                        None => None,
+                    }
+                }
+                input = signals_and_ticks.next() => input,
+                _ = &mut debounce, if dirty => {
+                    log::debug!("Debounce quiet period elapsed, updating workspace names");
+                    dirty = false;
+                    update_workspace_names(&i3, &config).await??;
+                    continue;
+                }
+            };
+
+            let input = match input {
+                Some(input) => input,
+                None => break,
+            };
+
+            match input {
+                Input::I3(Event::Window(window_data)) => {
                     match window_data.change {
                         WindowChange::New
                         | WindowChange::Close
@@ -149,12 +220,13 @@ async fn main() -> Result<()> {
                         | WindowChange::Floating => {
                             log::debug!("WindowChange");
                             // new, close, move, floating (?)
-                            update_workspace_names(&tx).await;
+                            dirty = true;
+                            debounce.as_mut().reset(tokio::time::Instant::now() + config.debounce);
                         }
                         _ => {}
                     }
                 }
-                Event::Workspace(workspace_data) => {
+                Input::I3(Event::Workspace(workspace_data)) => {
                     // init
                     // empty
                     // reload
@@ -164,34 +236,26 @@ async fn main() -> Result<()> {
                     match workspace_data.change {
                         WorkspaceChange::Init | WorkspaceChange::Empty | WorkspaceChange::Move => {
                             log::debug!("WorkspaceChange");
-                            update_workspace_names(&tx).await;
+                            dirty = true;
+                            debounce.as_mut().reset(tokio::time::Instant::now() + config.debounce);
                         }
                         _ => {}
                     }
                 }
-                _ => {}
-            }
-        }
-        log::debug!("Sender loop ended");
-        Ok::<_, Error>(())
-    });
-
-    let r_handle = tokio::spawn(async move {
-        let mut i3 = I3::connect().await?;
-        while let Some(cmd) = rx.recv().await {
-            match cmd {
-                Command::RunCommand { payload, resp } => {
-                    log::debug!("RunCommand {}", payload);
-                    let res = i3.run_command(payload).await;
-                    let _ = resp.send(res);
+                Input::I3(_) => {}
+                Input::Tick => {
+                    log::debug!("Resync tick");
+                    dirty = true;
+                    debounce.as_mut().reset(tokio::time::Instant::now() + config.debounce);
                 }
-                Command::GetTree { resp } => {
-                    let res = i3.get_tree().await;
-                    let _ = resp.send(res);
+                Input::Signal(kind) => {
+                    log::debug!("Received signal {:?}, restoring workspace names", kind);
+                    restore_workspace_names(&i3, &original_names).await?;
+                    break;
                 }
             }
         }
-        log::debug!("Receiver loop ended");
+        log::debug!("Sender loop ended");
         Ok::<_, Error>(())
     });
 